@@ -6,6 +6,15 @@
 // CAL supports GCM with:
 // - tag lenght must be <12,16>
 // - nonce lenght must be <7,13>
+//
+// The "must be odd number" rule above is CAL's restriction on a caller-chosen CCM tag length
+// (`AeadWithShortenedTag`): the library encodes a shortened tag's byte count into the M field of
+// the CCM mode byte, which only has odd encodings free in CAL's implementation. The PSA default
+// CCM tag (16 bytes, i.e. `AeadWithDefaultLengthTag`) never goes through that encoding -- it's
+// CAL's fixed, pre-selected mode byte -- so it is unaffected and 16 is accepted despite being even.
+
+#[cfg(feature = "crypto-authlib-software-fallback")]
+mod software_fallback;
 
 use super::Provider;
 use crate::authenticators::ApplicationName;
@@ -14,9 +23,46 @@ use log::error;
 use parsec_interface::operations::psa_algorithm::{Aead, AeadWithDefaultLengthTag};
 use parsec_interface::operations::{psa_aead_decrypt, psa_aead_encrypt};
 use parsec_interface::requests::{ProviderId, ResponseStatus, Result};
+use std::ops::RangeInclusive;
 
 const DEFAULT_TAG_LENGTH: usize = 16;
 
+const CCM_TAG_LENGTH_RANGE: RangeInclusive<usize> = 4..=16;
+const CCM_NONCE_LENGTH_RANGE: RangeInclusive<usize> = 7..=13;
+const GCM_TAG_LENGTH_RANGE: RangeInclusive<usize> = 12..=16;
+const GCM_NONCE_LENGTH_RANGE: RangeInclusive<usize> = 7..=13;
+
+// Whether the CAL hardware's own nonce/tag window (documented at the top of this file) accepts
+// this request. The odd-tag-length rule only applies to a caller-chosen (`AeadWithShortenedTag`)
+// length, per the rationale at the top of this file: the PSA default tag length (16 bytes for
+// both CCM and GCM) takes CAL's separate fixed-mode code path and is always accepted.
+fn hardware_window_ok(alg: &Aead, nonce_length: usize, tag_length: usize) -> bool {
+    let (tag_range, nonce_range) = if is_ccm_selected(alg) {
+        (CCM_TAG_LENGTH_RANGE, CCM_NONCE_LENGTH_RANGE)
+    } else {
+        (GCM_TAG_LENGTH_RANGE, GCM_NONCE_LENGTH_RANGE)
+    };
+    let is_shortened_tag = matches!(alg, Aead::AeadWithShortenedTag { .. });
+
+    tag_range.contains(&tag_length)
+        && !(is_ccm_selected(alg) && is_shortened_tag && tag_length % 2 == 0)
+        && nonce_range.contains(&nonce_length)
+}
+
+// Rejects a request whose nonce/tag lengths fall outside the CAL hardware window, so the caller
+// gets a precise PSA error before it reaches the device.
+fn validate_aead_lengths(alg: &Aead, nonce_length: usize, tag_length: usize) -> Result<()> {
+    if hardware_window_ok(alg, nonce_length, tag_length) {
+        return Ok(());
+    }
+
+    error!(
+        "aead operation failed, nonce length {} / tag length {} is not supported by CAL",
+        nonce_length, tag_length
+    );
+    Err(ResponseStatus::PsaErrorInvalidArgument)
+}
+
 pub fn get_tag_length(alg: &Aead) -> Option<usize> {
     match alg {
         Aead::AeadWithDefaultLengthTag(AeadWithDefaultLengthTag::Ccm) => Some(DEFAULT_TAG_LENGTH),
@@ -44,16 +90,145 @@ pub fn is_ccm_selected(alg: &Aead) -> bool {
     )
 }
 
+// Default plaintext chunk size for the streaming AEAD mode, modeled on the OpenPGP
+// AEAD-encrypted-data construction (RFC 4880bis): large enough to amortize per-chunk overhead,
+// small enough to bound peak memory on a host fronting an ATECC.
+const DEFAULT_STREAM_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+// Combines the base nonce with a big-endian chunk counter written over its trailing bytes, so
+// every chunk (and the final length-authenticating chunk) is encrypted under a distinct nonce.
+fn chunk_nonce(base_nonce: &[u8], chunk_index: u32) -> Vec<u8> {
+    let mut nonce = base_nonce.to_vec();
+    let counter = chunk_index.to_be_bytes();
+    let len = nonce.len();
+    nonce[len - counter.len()..].copy_from_slice(&counter);
+    nonce
+}
+
+// Associated data for the final, zero-length chunk: it binds the total chunk count and the
+// overall plaintext length into the last tag, so truncating or reordering chunks is detected.
+fn final_chunk_associated_data(chunk_count: u32, plaintext_length: usize) -> Vec<u8> {
+    let mut associated_data = Vec::with_capacity(12);
+    associated_data.extend_from_slice(&chunk_count.to_be_bytes());
+    associated_data.extend_from_slice(&(plaintext_length as u64).to_be_bytes());
+    associated_data
+}
+
+const STREAM_SEGMENT_LENGTH_PREFIX_SIZE: usize = 4;
+
+// Appends one stream segment to `output`: a 4-byte big-endian length prefix for the plaintext
+// chunk size, followed by the chunk ciphertext, followed by its tag. The explicit prefix makes
+// the stream self-describing, so a reader never has to guess a data chunk's size from the
+// caller's `chunk_size` -- the final (zero-length) chunk is just another segment with prefix 0.
+// Callers must keep `data_length` within `u32::MAX` (`psa_aead_encrypt_stream` enforces this on
+// `chunk_size` before it ever reaches here); it holds trivially for the final, zero-length chunk.
+fn write_stream_segment(
+    output: &mut Vec<u8>,
+    data_length: usize,
+    ciphertext_chunk: &[u8],
+    tag: &[u8],
+) {
+    debug_assert!(data_length <= u32::MAX as usize);
+    output.extend_from_slice(&(data_length as u32).to_be_bytes());
+    output.extend_from_slice(ciphertext_chunk);
+    output.extend_from_slice(tag);
+}
+
+// Reads the next stream segment from `ciphertext` at `offset`, returning its ciphertext slice,
+// tag slice, and the offset of the following segment. The length prefix is bounded only by
+// `ciphertext`'s own size -- not by the caller's `chunk_size` -- so a stream can be decrypted
+// with a different `chunk_size` than it was encrypted with; `segment_end > ciphertext.len()`
+// below is what actually keeps this from addressing out of bounds.
+fn read_stream_segment(
+    ciphertext: &[u8],
+    offset: usize,
+    tag_length: usize,
+) -> Result<(&[u8], &[u8], usize)> {
+    if ciphertext.len().saturating_sub(offset) < STREAM_SEGMENT_LENGTH_PREFIX_SIZE {
+        error!("aead_decrypt_stream failed, truncated chunk length prefix");
+        return Err(ResponseStatus::PsaErrorInvalidSignature);
+    }
+    let mut length_prefix = [0u8; STREAM_SEGMENT_LENGTH_PREFIX_SIZE];
+    length_prefix.copy_from_slice(&ciphertext[offset..offset + STREAM_SEGMENT_LENGTH_PREFIX_SIZE]);
+    let data_length = u32::from_be_bytes(length_prefix) as usize;
+
+    let data_start = offset + STREAM_SEGMENT_LENGTH_PREFIX_SIZE;
+    let segment_end = data_start
+        .checked_add(data_length)
+        .and_then(|end| end.checked_add(tag_length))
+        .ok_or(ResponseStatus::PsaErrorInvalidArgument)?;
+    if segment_end > ciphertext.len() {
+        error!("aead_decrypt_stream failed, truncated chunk or tag");
+        return Err(ResponseStatus::PsaErrorInvalidSignature);
+    }
+
+    let (data, tag) = ciphertext[data_start..segment_end].split_at(data_length);
+    Ok((data, tag, segment_end))
+}
+
+// Splits a combined `ciphertext || tag` buffer into its two parts, the inverse of
+// `psa_aead_encrypt_internal`'s `ciphertext.extend(tag)`. Used by `psa_aead_decrypt_internal` to
+// adapt the combined wire layout onto the detached `psa_aead_open_internal` API below.
+fn split_trailing_tag(combined: Vec<u8>, tag_length: usize) -> Result<(Vec<u8>, Vec<u8>)> {
+    if combined.len() < tag_length {
+        error!(
+            "aead_decrypt failed, ciphertext shorter than the tag length {}",
+            tag_length
+        );
+        return Err(ResponseStatus::PsaErrorInvalidArgument);
+    }
+
+    let mut ciphertext = combined;
+    let tag = ciphertext.split_off(ciphertext.len() - tag_length);
+    Ok((ciphertext, tag))
+}
+
 impl Provider {
-    pub(super) fn psa_aead_encrypt_internal(
+    // Encrypts `op.plaintext`, returning the ciphertext and authentication tag as separate
+    // buffers (mirroring the BoringSSL-style `seal`/`open` split), so a caller with its own wire
+    // framing is not forced to accept them concatenated. `psa_aead_encrypt_internal` below is a
+    // thin wrapper over this that restores the combined `ciphertext || tag` layout.
+    pub(super) fn psa_aead_seal_internal(
         &self,
         app_name: ApplicationName,
         op: psa_aead_encrypt::Operation,
-    ) -> Result<psa_aead_encrypt::Result> {
+    ) -> Result<(Vec<u8>, Vec<u8>)> {
         match get_tag_length(&op.alg) {
             Some(tag_length) => {
                 let key_triple =
                     KeyTriple::new(app_name, ProviderId::CryptoAuthLib, op.key_name.clone());
+
+                // The CAL nonce/tag window is checked first, ahead of `op.validate()` and the
+                // device dispatch below, so a length CAL can never support is rejected with a
+                // precise error instead of being forwarded to PSA-level or hardware validation.
+                if !hardware_window_ok(&op.alg, op.nonce.len(), tag_length) {
+                    #[cfg(feature = "crypto-authlib-software-fallback")]
+                    if self.software_fallback_enabled
+                        && software_fallback::supports(&op.alg, op.nonce.len(), tag_length)
+                    {
+                        let key_attributes = self.key_info_store.get_key_attributes(&key_triple)?;
+                        if software_fallback::permitted(
+                            key_attributes,
+                            software_fallback::Direction::Encrypt,
+                        ) {
+                            op.validate(key_attributes)?;
+                            let key_id = self.key_info_store.get_key_id::<u8>(&key_triple)?;
+                            let key_material = self.export_key_material(key_id)?;
+                            let mut combined = software_fallback::encrypt(
+                                &op.alg,
+                                &key_material,
+                                &op.nonce,
+                                tag_length,
+                                &op.additional_data,
+                                &op.plaintext,
+                            )?;
+                            let tag = combined.split_off(combined.len() - tag_length);
+                            return Ok((combined, tag));
+                        }
+                    }
+                    validate_aead_lengths(&op.alg, op.nonce.len(), tag_length)?;
+                }
+
                 let key_id = self.key_info_store.get_key_id::<u8>(&key_triple)?;
                 let key_attributes = self.key_info_store.get_key_attributes(&key_triple)?;
                 op.validate(key_attributes)?;
@@ -77,13 +252,7 @@ impl Provider {
                     .device
                     .aead_encrypt(aead_algorithm, key_id, &mut plaintext)
                 {
-                    Ok(tag) => {
-                        plaintext.extend(tag);
-
-                        Ok(psa_aead_encrypt::Result {
-                            ciphertext: plaintext.into(),
-                        })
-                    }
+                    Ok(tag) => Ok((plaintext, tag)),
                     Err(error) => {
                         error!("aead_encrypt failed CAL error {}.", error);
                         Err(ResponseStatus::PsaErrorGenericError)
@@ -97,27 +266,85 @@ impl Provider {
         }
     }
 
-    pub(super) fn psa_aead_decrypt_internal(
+    pub(super) fn psa_aead_encrypt_internal(
+        &self,
+        app_name: ApplicationName,
+        op: psa_aead_encrypt::Operation,
+    ) -> Result<psa_aead_encrypt::Result> {
+        let (mut ciphertext, tag) = self.psa_aead_seal_internal(app_name, op)?;
+        ciphertext.extend(tag);
+        Ok(psa_aead_encrypt::Result {
+            ciphertext: ciphertext.into(),
+        })
+    }
+
+    // Decrypts `op.ciphertext` against an explicit, out-of-band `tag` (the inverse of
+    // `psa_aead_seal_internal`), instead of assuming the tag is the trailing `tag_length` bytes
+    // of the ciphertext buffer. `psa_aead_decrypt_internal` below is a thin wrapper that peels
+    // the trailing tag off a combined `ciphertext || tag` buffer and calls this.
+    pub(super) fn psa_aead_open_internal(
         &self,
         app_name: ApplicationName,
         op: psa_aead_decrypt::Operation,
+        tag: &[u8],
     ) -> Result<psa_aead_decrypt::Result> {
         match get_tag_length(&op.alg) {
             Some(tag_length) => {
                 let key_triple =
                     KeyTriple::new(app_name, ProviderId::CryptoAuthLib, op.key_name.clone());
+
+                if tag.len() != tag_length {
+                    error!(
+                        "aead_decrypt failed, tag length {} does not match the expected {}",
+                        tag.len(),
+                        tag_length
+                    );
+                    return Err(ResponseStatus::PsaErrorInvalidArgument);
+                }
+
+                // The CAL nonce/tag window is checked first, ahead of `op.validate()` and the
+                // device dispatch below, so a length CAL can never support is rejected with a
+                // precise error instead of being forwarded to PSA-level or hardware validation.
+                if !hardware_window_ok(&op.alg, op.nonce.len(), tag_length) {
+                    #[cfg(feature = "crypto-authlib-software-fallback")]
+                    if self.software_fallback_enabled
+                        && software_fallback::supports(&op.alg, op.nonce.len(), tag_length)
+                    {
+                        let key_attributes = self.key_info_store.get_key_attributes(&key_triple)?;
+                        if software_fallback::permitted(
+                            key_attributes,
+                            software_fallback::Direction::Decrypt,
+                        ) {
+                            op.validate(key_attributes)?;
+                            let key_id = self.key_info_store.get_key_id::<u8>(&key_triple)?;
+                            let key_material = self.export_key_material(key_id)?;
+                            let mut combined = op.ciphertext.to_vec();
+                            combined.extend_from_slice(tag);
+                            let plaintext = software_fallback::decrypt(
+                                &op.alg,
+                                &key_material,
+                                &op.nonce,
+                                tag_length,
+                                &op.additional_data,
+                                &combined,
+                            )?;
+                            return Ok(psa_aead_decrypt::Result {
+                                plaintext: plaintext.into(),
+                            });
+                        }
+                    }
+                    validate_aead_lengths(&op.alg, op.nonce.len(), tag_length)?;
+                }
+
                 let key_id = self.key_info_store.get_key_id::<u8>(&key_triple)?;
                 let key_attributes = self.key_info_store.get_key_attributes(&key_triple)?;
                 op.validate(key_attributes)?;
 
                 let mut ciphertext: Vec<_> = op.ciphertext.to_vec();
-                let tag: Vec<_> = ciphertext
-                    .drain((ciphertext.len() - tag_length)..)
-                    .collect();
 
                 let aead_param_gcm = rust_cryptoauthlib::AeadParam {
                     nonce: op.nonce.to_vec(),
-                    tag: Some(tag),
+                    tag: Some(tag.to_vec()),
                     additional_data: Some(op.additional_data.to_vec()),
                     ..Default::default()
                 };
@@ -152,4 +379,379 @@ impl Provider {
             }
         }
     }
+
+    // Reads a key's raw material back out of its CAL slot, for the software fallback only. This
+    // is deliberately not the key info store: the store holds the slot id CAL dispatches device
+    // operations with, not key bytes, so it can never answer this for any key (exportable or
+    // not). The device export itself is CAL's own mechanism for reading a slot configured to
+    // allow it -- `software_fallback::permitted` is what gates which keys may reach this call.
+    #[cfg(feature = "crypto-authlib-software-fallback")]
+    fn export_key_material(&self, key_id: u8) -> Result<Vec<u8>> {
+        self.device.export_key(key_id).map_err(|error| {
+            error!("export_key failed CAL error {}.", error);
+            ResponseStatus::PsaErrorGenericError
+        })
+    }
+
+    pub(super) fn psa_aead_decrypt_internal(
+        &self,
+        app_name: ApplicationName,
+        op: psa_aead_decrypt::Operation,
+    ) -> Result<psa_aead_decrypt::Result> {
+        let tag_length = match get_tag_length(&op.alg) {
+            Some(tag_length) => tag_length,
+            None => {
+                error!("aead_decrypt failed, algorithm not supported");
+                return Err(ResponseStatus::PsaErrorNotSupported);
+            }
+        };
+
+        let (ciphertext, tag) = split_trailing_tag(op.ciphertext.to_vec(), tag_length)?;
+        let op = psa_aead_decrypt::Operation {
+            ciphertext: ciphertext.into(),
+            ..op
+        };
+
+        self.psa_aead_open_internal(app_name, op, &tag)
+    }
+
+    // Encrypts a single chunk (possibly the zero-length final chunk) under `key_id`, returning
+    // the chunk ciphertext and its tag separately so callers can lay them out on the wire.
+    fn aead_encrypt_chunk(
+        &self,
+        alg: &Aead,
+        key_id: u8,
+        nonce: &[u8],
+        tag_length: usize,
+        additional_data: &[u8],
+        plaintext_chunk: &[u8],
+    ) -> Result<(Vec<u8>, Vec<u8>)> {
+        let aead_param = rust_cryptoauthlib::AeadParam {
+            nonce: nonce.to_vec(),
+            tag_length: Some(tag_length as u8),
+            additional_data: Some(additional_data.to_vec()),
+            ..Default::default()
+        };
+        let aead_algorithm = if is_ccm_selected(alg) {
+            rust_cryptoauthlib::AeadAlgorithm::Ccm(aead_param)
+        } else {
+            rust_cryptoauthlib::AeadAlgorithm::Gcm(aead_param)
+        };
+
+        let mut buffer = plaintext_chunk.to_vec();
+        match self
+            .device
+            .aead_encrypt(aead_algorithm, key_id, &mut buffer)
+        {
+            Ok(tag) => Ok((buffer, tag)),
+            Err(error) => {
+                error!("aead_encrypt_stream failed CAL error {}.", error);
+                Err(ResponseStatus::PsaErrorGenericError)
+            }
+        }
+    }
+
+    // Decrypts and verifies a single chunk under `key_id`. Returns the recovered plaintext chunk
+    // (empty for the final chunk) or `PsaErrorInvalidSignature` if the tag does not verify.
+    fn aead_decrypt_chunk(
+        &self,
+        alg: &Aead,
+        key_id: u8,
+        nonce: &[u8],
+        additional_data: &[u8],
+        ciphertext_chunk: &[u8],
+        tag: &[u8],
+    ) -> Result<Vec<u8>> {
+        let aead_param = rust_cryptoauthlib::AeadParam {
+            nonce: nonce.to_vec(),
+            tag: Some(tag.to_vec()),
+            additional_data: Some(additional_data.to_vec()),
+            ..Default::default()
+        };
+        let aead_algorithm = if is_ccm_selected(alg) {
+            rust_cryptoauthlib::AeadAlgorithm::Ccm(aead_param)
+        } else {
+            rust_cryptoauthlib::AeadAlgorithm::Gcm(aead_param)
+        };
+
+        let mut buffer = ciphertext_chunk.to_vec();
+        match self
+            .device
+            .aead_decrypt(aead_algorithm, key_id, &mut buffer)
+        {
+            Ok(true) => Ok(buffer),
+            Ok(false) => {
+                error!("aead_decrypt_stream chunk authentication failed");
+                Err(ResponseStatus::PsaErrorInvalidSignature)
+            }
+            Err(error) => {
+                error!("aead_decrypt_stream error {}", error);
+                Err(ResponseStatus::PsaErrorInvalidSignature)
+            }
+        }
+    }
+
+    // Streaming AEAD encryption that processes `op.plaintext` one chunk at a time rather than in
+    // a single device call, bounding each individual device/cipher buffer to one chunk. `op`
+    // itself still arrives with the whole plaintext in memory -- `psa_aead_encrypt::Operation` is
+    // not an incremental input, so that part is unavoidable at this layer -- but this method does
+    // not make a second full-size copy of it or of the output on top of that. Segments are
+    // self-describing length-prefixed `ciphertext || tag` pairs (see `write_stream_segment`),
+    // with a trailing zero-length segment whose tag authenticates the total chunk count and
+    // plaintext length so the stream cannot be truncated or reordered undetected.
+    pub(super) fn psa_aead_encrypt_stream(
+        &self,
+        app_name: ApplicationName,
+        op: psa_aead_encrypt::Operation,
+        chunk_size: Option<usize>,
+    ) -> Result<psa_aead_encrypt::Result> {
+        let tag_length = match get_tag_length(&op.alg) {
+            Some(tag_length) => tag_length,
+            None => {
+                error!("aead_encrypt_stream failed, algorithm not supported");
+                return Err(ResponseStatus::PsaErrorNotSupported);
+            }
+        };
+
+        let key_triple = KeyTriple::new(app_name, ProviderId::CryptoAuthLib, op.key_name.clone());
+        let key_id = self.key_info_store.get_key_id::<u8>(&key_triple)?;
+        let key_attributes = self.key_info_store.get_key_attributes(&key_triple)?;
+        op.validate(key_attributes)?;
+        validate_aead_lengths(&op.alg, op.nonce.len(), tag_length)?;
+
+        let chunk_size = chunk_size.unwrap_or(DEFAULT_STREAM_CHUNK_SIZE).max(1);
+        if chunk_size > u32::MAX as usize {
+            error!(
+                "aead_encrypt_stream failed, chunk_size {} exceeds the {} byte framing limit",
+                chunk_size,
+                u32::MAX
+            );
+            return Err(ResponseStatus::PsaErrorInvalidArgument);
+        }
+
+        let mut output = Vec::new();
+        let mut chunk_index: u32 = 0;
+
+        for plaintext_chunk in op.plaintext.chunks(chunk_size) {
+            let nonce = chunk_nonce(&op.nonce, chunk_index);
+            let (ciphertext_chunk, tag) = self.aead_encrypt_chunk(
+                &op.alg,
+                key_id,
+                &nonce,
+                tag_length,
+                &op.additional_data,
+                plaintext_chunk,
+            )?;
+            write_stream_segment(&mut output, plaintext_chunk.len(), &ciphertext_chunk, &tag);
+            chunk_index = chunk_index
+                .checked_add(1)
+                .ok_or(ResponseStatus::PsaErrorInvalidArgument)?;
+        }
+
+        let final_nonce = chunk_nonce(&op.nonce, chunk_index);
+        let final_aad = final_chunk_associated_data(chunk_index, op.plaintext.len());
+        let (_, final_tag) =
+            self.aead_encrypt_chunk(&op.alg, key_id, &final_nonce, tag_length, &final_aad, &[])?;
+        write_stream_segment(&mut output, 0, &[], &final_tag);
+
+        Ok(psa_aead_encrypt::Result {
+            ciphertext: output.into(),
+        })
+    }
+
+    // Streaming AEAD decryption, the inverse of `psa_aead_encrypt_stream`. Every chunk tag and
+    // the final length-authenticating tag must verify, or the whole operation fails with
+    // `PsaErrorInvalidSignature`. `_chunk_size` is accepted only to keep the call signature
+    // symmetric with the encrypt side: the wire format is self-describing (`read_stream_segment`
+    // trusts the length prefix, bounded by `ciphertext`'s own size), so decoding never needs to
+    // know what `chunk_size` the stream was encrypted with.
+    pub(super) fn psa_aead_decrypt_stream(
+        &self,
+        app_name: ApplicationName,
+        op: psa_aead_decrypt::Operation,
+        _chunk_size: Option<usize>,
+    ) -> Result<psa_aead_decrypt::Result> {
+        let tag_length = match get_tag_length(&op.alg) {
+            Some(tag_length) => tag_length,
+            None => {
+                error!("aead_decrypt_stream failed, algorithm not supported");
+                return Err(ResponseStatus::PsaErrorNotSupported);
+            }
+        };
+
+        let key_triple = KeyTriple::new(app_name, ProviderId::CryptoAuthLib, op.key_name.clone());
+        let key_id = self.key_info_store.get_key_id::<u8>(&key_triple)?;
+        let key_attributes = self.key_info_store.get_key_attributes(&key_triple)?;
+        op.validate(key_attributes)?;
+        validate_aead_lengths(&op.alg, op.nonce.len(), tag_length)?;
+
+        let ciphertext = op.ciphertext.to_vec();
+        let mut plaintext = Vec::with_capacity(ciphertext.len());
+        let mut chunk_index: u32 = 0;
+        let mut offset = 0usize;
+
+        loop {
+            let (ciphertext_chunk, tag, next_offset) =
+                read_stream_segment(&ciphertext, offset, tag_length)?;
+
+            if ciphertext_chunk.is_empty() {
+                let final_nonce = chunk_nonce(&op.nonce, chunk_index);
+                let final_aad = final_chunk_associated_data(chunk_index, plaintext.len());
+                let _ =
+                    self.aead_decrypt_chunk(&op.alg, key_id, &final_nonce, &final_aad, &[], tag)?;
+                offset = next_offset;
+                break;
+            }
+
+            let nonce = chunk_nonce(&op.nonce, chunk_index);
+            let decrypted = self.aead_decrypt_chunk(
+                &op.alg,
+                key_id,
+                &nonce,
+                &op.additional_data,
+                ciphertext_chunk,
+                tag,
+            )?;
+            plaintext.extend(decrypted);
+            offset = next_offset;
+            chunk_index = chunk_index
+                .checked_add(1)
+                .ok_or(ResponseStatus::PsaErrorInvalidArgument)?;
+        }
+
+        if offset != ciphertext.len() {
+            error!("aead_decrypt_stream failed, trailing data after final chunk");
+            return Err(ResponseStatus::PsaErrorInvalidSignature);
+        }
+
+        Ok(psa_aead_decrypt::Result {
+            plaintext: plaintext.into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds a stream buffer out of `chunk_lengths` (mirroring what `psa_aead_encrypt_stream`
+    // writes, minus real encryption) and walks it back with `read_stream_segment`, returning the
+    // recovered data-chunk lengths. Used to exercise the framing in isolation from the CAL
+    // device, which this snapshot has no mock for.
+    fn round_trip_segment_lengths(
+        chunk_lengths: &[usize],
+        tag_length: usize,
+    ) -> Result<Vec<usize>> {
+        let mut buffer = Vec::new();
+        for &len in chunk_lengths {
+            write_stream_segment(&mut buffer, len, &vec![0xAB; len], &vec![0xCD; tag_length]);
+        }
+        write_stream_segment(&mut buffer, 0, &[], &vec![0xEF; tag_length]);
+
+        let mut recovered = Vec::new();
+        let mut offset = 0;
+        loop {
+            let (data, _tag, next_offset) = read_stream_segment(&buffer, offset, tag_length)?;
+            offset = next_offset;
+            if data.is_empty() {
+                break;
+            }
+            recovered.push(data.len());
+        }
+        assert_eq!(offset, buffer.len());
+        Ok(recovered)
+    }
+
+    #[test]
+    fn stream_framing_partial_last_chunk() {
+        // A full-size chunk followed by a short final data chunk used to be misread by the old
+        // `min(chunk_size, remaining - tag_length)` rule, which didn't account for the trailing
+        // zero-length chunk's own tag still to come.
+        let chunk_size = 64;
+        assert_eq!(
+            round_trip_segment_lengths(&[chunk_size, 10], 16).unwrap(),
+            vec![chunk_size, 10]
+        );
+    }
+
+    #[test]
+    fn stream_framing_exact_multiple() {
+        let chunk_size = 16;
+        assert_eq!(
+            round_trip_segment_lengths(&[chunk_size, chunk_size], 16).unwrap(),
+            vec![chunk_size, chunk_size]
+        );
+    }
+
+    #[test]
+    fn stream_framing_empty_plaintext() {
+        assert_eq!(
+            round_trip_segment_lengths(&[], 16).unwrap(),
+            Vec::<usize>::new()
+        );
+    }
+
+    #[test]
+    fn stream_framing_single_chunk() {
+        assert_eq!(round_trip_segment_lengths(&[100], 16).unwrap(), vec![100]);
+    }
+
+    #[test]
+    fn stream_framing_is_independent_of_the_decrypter_chunk_size() {
+        // A stream written with one chunk size decodes correctly read back with `chunk_size`
+        // playing no part at all, since `read_stream_segment` no longer takes one: a segment
+        // larger than whatever the decrypter might otherwise have guessed must still decode.
+        assert_eq!(
+            round_trip_segment_lengths(&[4 * 1024 * 1024], 16).unwrap(),
+            vec![4 * 1024 * 1024]
+        );
+    }
+
+    #[test]
+    fn stream_framing_rejects_truncated_tag() {
+        let mut buffer = Vec::new();
+        write_stream_segment(&mut buffer, 8, &[0xAB; 8], &[0xCD; 16]);
+        buffer.truncate(buffer.len() - 1);
+
+        assert!(read_stream_segment(&buffer, 0, 16).is_err());
+    }
+
+    #[test]
+    fn stream_framing_rejects_chunk_length_past_the_end_of_the_buffer() {
+        let mut buffer = Vec::new();
+        write_stream_segment(&mut buffer, 8, &[0xAB; 8], &[0xCD; 16]);
+        // Claim a data length the buffer doesn't actually have room for.
+        let bogus_length = (buffer.len() as u32 + 1).to_be_bytes();
+        buffer[0..4].copy_from_slice(&bogus_length);
+
+        assert!(read_stream_segment(&buffer, 0, 16).is_err());
+    }
+
+    #[test]
+    fn hardware_window_ok_accepts_default_ccm_tag_but_rejects_even_shortened_tag() {
+        let default_tag = Aead::AeadWithDefaultLengthTag(AeadWithDefaultLengthTag::Ccm);
+        assert!(hardware_window_ok(&default_tag, 13, 16));
+
+        let shortened_even_tag = Aead::AeadWithShortenedTag {
+            aead_alg: AeadWithDefaultLengthTag::Ccm,
+            tag_length: 14,
+        };
+        assert!(!hardware_window_ok(&shortened_even_tag, 13, 14));
+    }
+
+    #[test]
+    fn split_trailing_tag_recovers_both_parts() {
+        let mut combined = b"ciphertext-bytes".to_vec();
+        combined.extend_from_slice(&[0xAB; 16]);
+
+        let (ciphertext, tag) = split_trailing_tag(combined, 16).unwrap();
+
+        assert_eq!(ciphertext, b"ciphertext-bytes");
+        assert_eq!(tag, vec![0xAB; 16]);
+    }
+
+    #[test]
+    fn split_trailing_tag_rejects_buffer_shorter_than_the_tag() {
+        assert!(split_trailing_tag(vec![0u8; 4], 16).is_err());
+    }
 }
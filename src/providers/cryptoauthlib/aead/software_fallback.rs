@@ -0,0 +1,280 @@
+// Copyright 2021 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Pure-Rust AEAD for the standards-compliant CCM/GCM parameter sets the ATECC device itself
+//! cannot produce: CCM's NIST-standard *even* tag lengths (CAL's hardware only accepts odd tag
+//! lengths for an explicit `AeadWithShortenedTag`) and GCM tag lengths below CAL's 12-byte floor.
+//! Only reached when the `crypto-authlib-software-fallback` feature is built in, the provider
+//! config has `software_fallback_enabled = true`, and the key's policy permits it -- see
+//! `permitted` below for what that actually checks.
+
+use aes::{Aes128, Aes192, Aes256};
+use aes_gcm::AesGcm;
+use ccm::aead::generic_array::GenericArray;
+use ccm::aead::{Aead, KeyInit, Payload};
+use ccm::consts::{U10, U12, U13, U14, U4, U6, U8};
+use ccm::Ccm;
+use parsec_interface::operations::psa_algorithm::Aead as AeadAlg;
+use parsec_interface::operations::psa_key_attributes::Attributes;
+use parsec_interface::requests::{ResponseStatus, Result};
+
+use super::is_ccm_selected;
+
+// The nonce length CAL itself already accepts (7..=13) is never the software fallback's
+// problem, so the fallback fixes it at the conventional value for each mode and only varies
+// the tag length, which is where the hardware refuses otherwise-valid PSA requests.
+const CCM_SOFTWARE_NONCE_LENGTH: usize = 13;
+const GCM_SOFTWARE_NONCE_LENGTH: usize = 12;
+
+/// Which direction of an AEAD operation is being gated, since a key's usage flags permit
+/// encryption and decryption independently.
+pub(super) enum Direction {
+    Encrypt,
+    Decrypt,
+}
+
+/// Whether this nonce/tag combination is one the software fallback implements. Deliberately
+/// narrow: it covers the standards-compliant sets CAL's hardware quirks put out of reach, not
+/// every PSA-legal nonce/tag pair.
+pub(super) fn supports(alg: &AeadAlg, nonce_length: usize, tag_length: usize) -> bool {
+    if is_ccm_selected(alg) {
+        nonce_length == CCM_SOFTWARE_NONCE_LENGTH && matches!(tag_length, 4 | 6 | 8 | 10 | 12 | 14)
+    } else {
+        nonce_length == GCM_SOFTWARE_NONCE_LENGTH && matches!(tag_length, 4 | 6 | 8 | 10)
+    }
+}
+
+/// Whether `key_attributes`' policy permits this key's material to be exported from the device
+/// and handled in software for the given `direction`. The PSA export usage flag is the only
+/// attribute that means "this key's bytes may leave the secure boundary" -- a key can be
+/// encrypt/decrypt-capable without being exportable, in which case it must stay on the device --
+/// so `export()` is required in addition to, not instead of, the usual per-direction usage flag
+/// that `op.validate()` already enforces against the operation itself.
+pub(super) fn permitted(key_attributes: &Attributes, direction: Direction) -> bool {
+    let usage_flags = &key_attributes.policy.usage_flags;
+    let direction_allowed = match direction {
+        Direction::Encrypt => usage_flags.encrypt(),
+        Direction::Decrypt => usage_flags.decrypt(),
+    };
+
+    usage_flags.export() && direction_allowed
+}
+
+macro_rules! ccm_seal {
+    ($cipher:ty, $tag_size:ty, $key:expr, $nonce:expr, $aad:expr, $plaintext:expr) => {{
+        let cipher = Ccm::<$cipher, $tag_size, U13>::new_from_slice($key)
+            .map_err(|_| ResponseStatus::PsaErrorInvalidArgument)?;
+        cipher
+            .encrypt(
+                GenericArray::<u8, U13>::from_slice($nonce),
+                Payload {
+                    msg: $plaintext,
+                    aad: $aad,
+                },
+            )
+            .map_err(|_| ResponseStatus::PsaErrorGenericError)
+    }};
+}
+
+macro_rules! ccm_open {
+    ($cipher:ty, $tag_size:ty, $key:expr, $nonce:expr, $aad:expr, $ciphertext_and_tag:expr) => {{
+        let cipher = Ccm::<$cipher, $tag_size, U13>::new_from_slice($key)
+            .map_err(|_| ResponseStatus::PsaErrorInvalidArgument)?;
+        cipher
+            .decrypt(
+                GenericArray::<u8, U13>::from_slice($nonce),
+                Payload {
+                    msg: $ciphertext_and_tag,
+                    aad: $aad,
+                },
+            )
+            .map_err(|_| ResponseStatus::PsaErrorInvalidSignature)
+    }};
+}
+
+macro_rules! gcm_seal {
+    ($cipher:ty, $tag_size:ty, $key:expr, $nonce:expr, $aad:expr, $plaintext:expr) => {{
+        let cipher = AesGcm::<$cipher, U12, $tag_size>::new_from_slice($key)
+            .map_err(|_| ResponseStatus::PsaErrorInvalidArgument)?;
+        cipher
+            .encrypt(
+                GenericArray::<u8, U12>::from_slice($nonce),
+                Payload {
+                    msg: $plaintext,
+                    aad: $aad,
+                },
+            )
+            .map_err(|_| ResponseStatus::PsaErrorGenericError)
+    }};
+}
+
+macro_rules! gcm_open {
+    ($cipher:ty, $tag_size:ty, $key:expr, $nonce:expr, $aad:expr, $ciphertext_and_tag:expr) => {{
+        let cipher = AesGcm::<$cipher, U12, $tag_size>::new_from_slice($key)
+            .map_err(|_| ResponseStatus::PsaErrorInvalidArgument)?;
+        cipher
+            .decrypt(
+                GenericArray::<u8, U12>::from_slice($nonce),
+                Payload {
+                    msg: $ciphertext_and_tag,
+                    aad: $aad,
+                },
+            )
+            .map_err(|_| ResponseStatus::PsaErrorInvalidSignature)
+    }};
+}
+
+// Dispatches a CCM seal/open by the exported key's actual byte length (AES-128/192/256), rather
+// than assuming AES-256: `key` comes from the device's own export path, so its length is
+// whatever the key was actually provisioned as.
+macro_rules! ccm_dispatch {
+    ($op:ident, $key:expr, $tag_size:ty, $nonce:expr, $aad:expr, $buf:expr) => {
+        match $key.len() {
+            16 => ccm_dispatch!(@$op Aes128, $tag_size, $key, $nonce, $aad, $buf),
+            24 => ccm_dispatch!(@$op Aes192, $tag_size, $key, $nonce, $aad, $buf),
+            32 => ccm_dispatch!(@$op Aes256, $tag_size, $key, $nonce, $aad, $buf),
+            _ => Err(ResponseStatus::PsaErrorInvalidArgument),
+        }
+    };
+    (@seal $cipher:ty, $tag_size:ty, $key:expr, $nonce:expr, $aad:expr, $buf:expr) => {
+        ccm_seal!($cipher, $tag_size, $key, $nonce, $aad, $buf)
+    };
+    (@open $cipher:ty, $tag_size:ty, $key:expr, $nonce:expr, $aad:expr, $buf:expr) => {
+        ccm_open!($cipher, $tag_size, $key, $nonce, $aad, $buf)
+    };
+}
+
+macro_rules! gcm_dispatch {
+    ($op:ident, $key:expr, $tag_size:ty, $nonce:expr, $aad:expr, $buf:expr) => {
+        match $key.len() {
+            16 => gcm_dispatch!(@$op Aes128, $tag_size, $key, $nonce, $aad, $buf),
+            24 => gcm_dispatch!(@$op Aes192, $tag_size, $key, $nonce, $aad, $buf),
+            32 => gcm_dispatch!(@$op Aes256, $tag_size, $key, $nonce, $aad, $buf),
+            _ => Err(ResponseStatus::PsaErrorInvalidArgument),
+        }
+    };
+    (@seal $cipher:ty, $tag_size:ty, $key:expr, $nonce:expr, $aad:expr, $buf:expr) => {
+        gcm_seal!($cipher, $tag_size, $key, $nonce, $aad, $buf)
+    };
+    (@open $cipher:ty, $tag_size:ty, $key:expr, $nonce:expr, $aad:expr, $buf:expr) => {
+        gcm_open!($cipher, $tag_size, $key, $nonce, $aad, $buf)
+    };
+}
+
+/// Seals `plaintext`, returning the combined ciphertext-then-tag buffer (matching the wire
+/// layout the hardware path already produces), using the raw software key `key` (16, 24, or 32
+/// bytes, selecting AES-128/192/256 respectively).
+pub(super) fn encrypt(
+    alg: &AeadAlg,
+    key: &[u8],
+    nonce: &[u8],
+    tag_length: usize,
+    additional_data: &[u8],
+    plaintext: &[u8],
+) -> Result<Vec<u8>> {
+    if is_ccm_selected(alg) {
+        match tag_length {
+            4 => ccm_dispatch!(seal, key, U4, nonce, additional_data, plaintext),
+            6 => ccm_dispatch!(seal, key, U6, nonce, additional_data, plaintext),
+            8 => ccm_dispatch!(seal, key, U8, nonce, additional_data, plaintext),
+            10 => ccm_dispatch!(seal, key, U10, nonce, additional_data, plaintext),
+            12 => ccm_dispatch!(seal, key, U12, nonce, additional_data, plaintext),
+            14 => ccm_dispatch!(seal, key, U14, nonce, additional_data, plaintext),
+            _ => Err(ResponseStatus::PsaErrorNotSupported),
+        }
+    } else {
+        match tag_length {
+            4 => gcm_dispatch!(seal, key, U4, nonce, additional_data, plaintext),
+            6 => gcm_dispatch!(seal, key, U6, nonce, additional_data, plaintext),
+            8 => gcm_dispatch!(seal, key, U8, nonce, additional_data, plaintext),
+            10 => gcm_dispatch!(seal, key, U10, nonce, additional_data, plaintext),
+            _ => Err(ResponseStatus::PsaErrorNotSupported),
+        }
+    }
+}
+
+/// Opens a combined ciphertext-then-tag buffer with the raw software key `key` (16, 24, or 32
+/// bytes, selecting AES-128/192/256 respectively), verifying the tag before returning any
+/// plaintext.
+pub(super) fn decrypt(
+    alg: &AeadAlg,
+    key: &[u8],
+    nonce: &[u8],
+    tag_length: usize,
+    additional_data: &[u8],
+    ciphertext_and_tag: &[u8],
+) -> Result<Vec<u8>> {
+    if is_ccm_selected(alg) {
+        match tag_length {
+            4 => ccm_dispatch!(open, key, U4, nonce, additional_data, ciphertext_and_tag),
+            6 => ccm_dispatch!(open, key, U6, nonce, additional_data, ciphertext_and_tag),
+            8 => ccm_dispatch!(open, key, U8, nonce, additional_data, ciphertext_and_tag),
+            10 => ccm_dispatch!(open, key, U10, nonce, additional_data, ciphertext_and_tag),
+            12 => ccm_dispatch!(open, key, U12, nonce, additional_data, ciphertext_and_tag),
+            14 => ccm_dispatch!(open, key, U14, nonce, additional_data, ciphertext_and_tag),
+            _ => Err(ResponseStatus::PsaErrorNotSupported),
+        }
+    } else {
+        match tag_length {
+            4 => gcm_dispatch!(open, key, U4, nonce, additional_data, ciphertext_and_tag),
+            6 => gcm_dispatch!(open, key, U6, nonce, additional_data, ciphertext_and_tag),
+            8 => gcm_dispatch!(open, key, U8, nonce, additional_data, ciphertext_and_tag),
+            10 => gcm_dispatch!(open, key, U10, nonce, additional_data, ciphertext_and_tag),
+            _ => Err(ResponseStatus::PsaErrorNotSupported),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parsec_interface::operations::psa_algorithm::AeadWithDefaultLengthTag;
+
+    #[test]
+    fn ccm_round_trips_for_every_supported_key_size() {
+        let alg = AeadAlg::AeadWithShortenedTag {
+            aead_alg: AeadWithDefaultLengthTag::Ccm,
+            tag_length: 12,
+        };
+        let nonce = [0u8; CCM_SOFTWARE_NONCE_LENGTH];
+        let aad = b"associated data";
+        let plaintext = b"a secret message";
+
+        for key_length in [16, 24, 32] {
+            let key = vec![0x42; key_length];
+            let ciphertext = encrypt(&alg, &key, &nonce, 12, aad, plaintext).unwrap();
+            let recovered = decrypt(&alg, &key, &nonce, 12, aad, &ciphertext).unwrap();
+            assert_eq!(recovered, plaintext);
+        }
+    }
+
+    #[test]
+    fn gcm_round_trips_for_every_supported_key_size() {
+        let alg = AeadAlg::AeadWithShortenedTag {
+            aead_alg: AeadWithDefaultLengthTag::Gcm,
+            tag_length: 8,
+        };
+        let nonce = [0u8; GCM_SOFTWARE_NONCE_LENGTH];
+        let aad = b"associated data";
+        let plaintext = b"a different secret";
+
+        for key_length in [16, 24, 32] {
+            let key = vec![0x24; key_length];
+            let ciphertext = encrypt(&alg, &key, &nonce, 8, aad, plaintext).unwrap();
+            let recovered = decrypt(&alg, &key, &nonce, 8, aad, &ciphertext).unwrap();
+            assert_eq!(recovered, plaintext);
+        }
+    }
+
+    #[test]
+    fn unsupported_key_length_is_rejected_instead_of_panicking() {
+        let alg = AeadAlg::AeadWithShortenedTag {
+            aead_alg: AeadWithDefaultLengthTag::Ccm,
+            tag_length: 12,
+        };
+        let nonce = [0u8; CCM_SOFTWARE_NONCE_LENGTH];
+        let key = vec![0x42; 20];
+
+        assert!(encrypt(&alg, &key, &nonce, 12, b"aad", b"plaintext").is_err());
+    }
+}